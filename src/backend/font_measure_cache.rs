@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use super::FONT_MEASURE;
+
+/// Key identifying a single cached text measurement.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct FontMeasureKey {
+    text_hash: u64,
+    font: String,
+    font_size: u32,
+    start: usize,
+    end: usize,
+}
+
+/// A small reusable cache for text-shaping measurements.
+///
+/// Shaping a string prefix is the dominant cost of text layout, and both the
+/// measure and arrange passes request the same widths every frame. This cache
+/// keys a measured width by `(text hash, font, font_size, byte range)` so repeated
+/// requests for an unchanged string are served without re-invoking [`FONT_MEASURE`].
+///
+/// Because the key includes the text hash, entries are self-invalidating: a change
+/// to the text simply produces new keys and the stale ones are never hit again.
+/// This lets measurements survive *across* arrange passes — e.g. when only the
+/// caret/selection moves over otherwise unchanged text, every prefix width is a
+/// cache hit. Call [`clear`] to reclaim the memory of text that is gone for good.
+///
+/// It lives next to `FONT_MEASURE` so any text-measuring layout can share it.
+///
+/// [`clear`]: FontMeasureCache::clear
+#[derive(Default)]
+pub struct FontMeasureCache {
+    entries: RefCell<HashMap<FontMeasureKey, f64>>,
+}
+
+impl FontMeasureCache {
+    pub fn new() -> Self {
+        FontMeasureCache::default()
+    }
+
+    /// Returns the measured width of `text[range]`, reusing a cached value when the
+    /// same slice of the same text was measured before.
+    pub fn measure(&self, text: &str, range: Range<usize>, font: &str, font_size: u32) -> f64 {
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+
+        let key = FontMeasureKey {
+            text_hash: hasher.finish(),
+            font: font.to_string(),
+            font_size,
+            start: range.start,
+            end: range.end,
+        };
+
+        if let Some(width) = self.entries.borrow().get(&key) {
+            return *width;
+        }
+
+        let width = match text.get(range) {
+            Some(part) => FONT_MEASURE.measure(part, font, font_size).0 as f64,
+            None => 0.0,
+        };
+
+        self.entries.borrow_mut().insert(key, width);
+        width
+    }
+
+    /// Drops all cached measurements, reclaiming the memory of text that is no
+    /// longer measured. Entries for changed text are never hit, so this is not
+    /// needed for correctness.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+}
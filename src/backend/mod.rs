@@ -0,0 +1,6 @@
+// The platform `FontMeasure` trait and the global `FONT_MEASURE` instance are
+// declared by the platform-specific backend modules (not shown in this snapshot).
+
+mod font_measure_cache;
+
+pub use self::font_measure_cache::FontMeasureCache;
@@ -1,17 +1,22 @@
 use std::{
     cell::{Cell, RefCell},
     collections::BTreeMap,
+    ops::Range,
     rc::Rc,
 };
 
 use dces::prelude::{Entity, EntityComponentManager};
 
+// Requires the `unicode-segmentation` crate; it must be listed under
+// `[dependencies]` in the workspace `Cargo.toml` (e.g. `unicode-segmentation = "1"`).
+use unicode_segmentation::UnicodeSegmentation;
+
 use crate::{
     application::Tree,
-    backend::{FontMeasure, FONT_MEASURE},
+    backend::FontMeasureCache,
     properties::{
-        Bounds, Constraint, Font, FontSize, Margin, Offset, Text, TextSelection, VerticalAlignment,
-        Visibility,
+        Bounds, Constraint, Font, FontSize, Margin, Offset, Text, TextDirection, TextSelection,
+        TextSpan, TextSpans, VerticalAlignment, Visibility,
     },
     structs::{DirtySize, Size, Spacer},
     theme::Theme,
@@ -20,17 +25,394 @@ use crate::{
 
 use super::Layout;
 
+/// Returns the byte offset of the grapheme-cluster boundary at `grapheme_index`.
+///
+/// `TextSelection` indices count grapheme clusters, not bytes or `char`s, so this
+/// maps a logical caret position onto a byte offset that can be used to slice the
+/// string. Indices beyond the end of the text clamp to `text.len()`, which always
+/// lands on a valid boundary and never panics mid-codepoint.
+pub fn grapheme_to_byte_offset(text: &str, grapheme_index: usize) -> usize {
+    text.grapheme_indices(true)
+        .nth(grapheme_index)
+        .map(|(offset, _)| offset)
+        .unwrap_or_else(|| text.len())
+}
+
+/// Returns the number of grapheme clusters that start before `byte_offset`.
+///
+/// For an offset on a cluster boundary this is the index of the cluster starting
+/// there; an offset inside a cluster rounds up to the index of the next cluster.
+///
+/// This is the inverse of [`grapheme_to_byte_offset`] used by callers (e.g. key
+/// handling and hit-testing) to turn a byte offset back into a logical caret
+/// position that stays consistent with the layout.
+pub fn byte_to_grapheme_offset(text: &str, byte_offset: usize) -> usize {
+    text.grapheme_indices(true)
+        .take_while(|(offset, _)| *offset < byte_offset)
+        .count()
+}
+
+/// Returns the strong direction of `c`, or `None` for neutral characters.
+fn strong_direction(c: char) -> Option<TextDirection> {
+    match c {
+        // Hebrew, Arabic, Syriac and Thaana blocks cover the common RTL scripts.
+        '\u{0590}'..='\u{05FF}'
+        | '\u{0600}'..='\u{06FF}'
+        | '\u{0700}'..='\u{074F}'
+        | '\u{0780}'..='\u{07BF}'
+        | '\u{FB1D}'..='\u{FDFF}'
+        | '\u{FE70}'..='\u{FEFF}' => Some(TextDirection::RightToLeft),
+        c if c.is_alphabetic() => Some(TextDirection::LeftToRight),
+        _ => None,
+    }
+}
+
+/// Splits `text[range]` into maximal runs of a single resolved direction.
+///
+/// Neutral characters attach to the preceding run so a trailing space keeps the
+/// direction of the word it follows.
+fn directional_runs(text: &str, range: Range<usize>) -> Vec<(Range<usize>, TextDirection)> {
+    let mut runs: Vec<(Range<usize>, TextDirection)> = Vec::new();
+
+    for (offset, c) in text[range.clone()].char_indices() {
+        let abs = range.start + offset;
+        let end = abs + c.len_utf8();
+        let dir = strong_direction(c);
+
+        match (runs.last_mut(), dir) {
+            (Some(last), Some(d)) if last.1 == d => last.0.end = end,
+            (Some(last), None) => last.0.end = end,
+            (_, Some(d)) => runs.push((abs..end, d)),
+            (None, None) => runs.push((abs..end, TextDirection::LeftToRight)),
+        }
+    }
+
+    runs
+}
+
+/// Measures `text[range]` summing each styled run with its own font metrics.
+///
+/// Uncovered stretches fall back to `default_font`/`default_size`, so an empty
+/// span list reduces to a single measurement of the whole range.
+fn measure_range_spans(
+    cache: &FontMeasureCache,
+    text: &str,
+    range: Range<usize>,
+    spans: &[TextSpan],
+    default_font: &str,
+    default_size: u32,
+) -> f64 {
+    if spans.is_empty() {
+        return cache.measure(text, range, default_font, default_size);
+    }
+
+    let mut width = 0.0;
+    let mut cursor = range.start;
+
+    while cursor < range.end {
+        match spans
+            .iter()
+            .find(|s| s.range.start <= cursor && cursor < s.range.end)
+        {
+            Some(span) => {
+                let seg_end = span.range.end.min(range.end);
+                width += cache.measure(
+                    text,
+                    cursor..seg_end,
+                    &span.font,
+                    span.font_size as u32,
+                );
+                cursor = seg_end;
+            }
+            None => {
+                let seg_end = spans
+                    .iter()
+                    .map(|s| s.range.start)
+                    .filter(|&start| start > cursor)
+                    .min()
+                    .unwrap_or(range.end)
+                    .min(range.end);
+                width += cache.measure(text, cursor..seg_end, default_font, default_size);
+                cursor = seg_end;
+            }
+        }
+    }
+
+    width
+}
+
+/// Computes the visual x offset of the caret at `caret_byte` within `line_range`.
+///
+/// Runs are accumulated left to right; the offset inside an RTL run is measured
+/// from the run's right edge so the logical caret index maps to the correct visual
+/// position for a single-direction line.
+///
+/// NOTE: the `Auto` path lays directional runs out in *logical* order and does not
+/// perform the full UAX #9 visual reordering, so caret x-positions for mixed
+/// LTR/RTL content are approximate. Pass an explicit `LeftToRight`/`RightToLeft`
+/// direction for a uniformly-directed line to get an exact offset.
+fn caret_visual_x(
+    cache: &FontMeasureCache,
+    text: &str,
+    line_range: Range<usize>,
+    caret_byte: usize,
+    font: &str,
+    font_size: u32,
+    direction: TextDirection,
+    spans: &[TextSpan],
+) -> f64 {
+    // An explicit LTR/RTL direction treats the whole line as one run; `Auto` splits
+    // the line into directional runs and accumulates their widths. Each measurement
+    // is summed run-by-run so per-span fonts contribute their own metrics.
+    match direction {
+        TextDirection::LeftToRight => {
+            return measure_range_spans(
+                cache,
+                text,
+                line_range.start..caret_byte,
+                spans,
+                font,
+                font_size,
+            );
+        }
+        TextDirection::RightToLeft => {
+            let line_width = measure_range_spans(cache, text, line_range.clone(), spans, font, font_size);
+            let within = measure_range_spans(
+                cache,
+                text,
+                line_range.start..caret_byte,
+                spans,
+                font,
+                font_size,
+            );
+            return line_width - within;
+        }
+        TextDirection::Auto => {}
+    }
+
+    // `Auto`: resolve the line's own runs. A uniform line (the common case, including
+    // a pure Arabic/Hebrew line) is positioned exactly by delegating to its resolved
+    // direction, so both caret endpoints land on the correct side. Truly mixed lines
+    // fall back to logical-order run accumulation, which is approximate (see the note
+    // on this function — no UAX #9 visual reordering).
+    let runs = directional_runs(text, line_range.clone());
+
+    if let Some((_, base)) = runs.first() {
+        if runs.iter().all(|(_, dir)| dir == base) {
+            let within =
+                measure_range_spans(cache, text, line_range.start..caret_byte, spans, font, font_size);
+            return match base {
+                TextDirection::RightToLeft => {
+                    let line_width =
+                        measure_range_spans(cache, text, line_range, spans, font, font_size);
+                    line_width - within
+                }
+                _ => within,
+            };
+        }
+    }
+
+    let mut x = 0.0;
+
+    for (run, dir) in runs {
+        let run_width = measure_range_spans(cache, text, run.clone(), spans, font, font_size);
+
+        if caret_byte <= run.start {
+            break;
+        }
+
+        if caret_byte >= run.end {
+            x += run_width;
+            continue;
+        }
+
+        let within = measure_range_spans(cache, text, run.start..caret_byte, spans, font, font_size);
+        x += match dir {
+            TextDirection::RightToLeft => run_width - within,
+            _ => within,
+        };
+        return x;
+    }
+
+    x
+}
+
+/// A single visual line produced by wrapping the text to the available width.
+#[derive(Clone, Debug, Default)]
+pub struct TextLine {
+    /// Byte range of the line within the full text.
+    pub byte_range: Range<usize>,
+    /// Measured width of the line in pixels.
+    pub width: f64,
+}
+
+/// Leading factor applied to the em size to derive a line height.
+///
+/// Matches the 1.2 default used by the SVG text renderer so wrapped lines keep an
+/// inter-line gap rather than butting carets together.
+const LINE_HEIGHT_FACTOR: f64 = 1.2;
+
+/// Returns the line height in pixels for the given `FontSize`.
+pub fn line_height(font_size: f64) -> f64 {
+    font_size * LINE_HEIGHT_FACTOR
+}
+
+/// Wraps `text` into visual lines that each fit within `max_width` pixels.
+///
+/// Wrapping happens at whitespace boundaries (greedy, like the SVG renderer), and
+/// explicit `\n` always starts a new line. A non-positive `max_width` disables
+/// wrapping and yields a single line per hard break. The returned ranges are byte
+/// offsets into `text` so callers can slice and hit-test without re-walking the
+/// grapheme boundaries.
+pub fn wrap_lines(
+    cache: &FontMeasureCache,
+    text: &str,
+    font: &str,
+    font_size: u32,
+    max_width: f64,
+) -> Vec<TextLine> {
+    let mut lines = Vec::new();
+
+    for paragraph in split_inclusive_newlines(text) {
+        let para_start = paragraph.as_ptr() as usize - text.as_ptr() as usize;
+        let content = paragraph.trim_end_matches('\n');
+
+        // Measure each grapheme's advance once and accumulate, so wrapping is linear
+        // instead of reshaping a growing prefix for every grapheme. Each measurement
+        // is memoised through the shared cache as well.
+        let graphemes: Vec<(usize, &str)> = content
+            .grapheme_indices(true)
+            .map(|(offset, g)| (para_start + offset, g))
+            .collect();
+        let n = graphemes.len();
+
+        let mut prefix = Vec::with_capacity(n + 1);
+        prefix.push(0.0);
+        for (abs, g) in &graphemes {
+            let advance = cache.measure(text, *abs..*abs + g.len(), font, font_size);
+            prefix.push(prefix[prefix.len() - 1] + advance);
+        }
+
+        let line_end = para_start + content.len();
+        let byte_at = |idx: usize| if idx < n { graphemes[idx].0 } else { line_end };
+
+        if max_width <= 0.0 || n == 0 {
+            lines.push(TextLine {
+                byte_range: para_start..line_end,
+                width: prefix[n],
+            });
+            continue;
+        }
+
+        let mut line_start_idx = 0;
+        let mut last_break: Option<usize> = None;
+        let mut i = 0;
+
+        while i < n {
+            let width = prefix[i + 1] - prefix[line_start_idx];
+
+            if width > max_width && i > line_start_idx {
+                let break_idx = last_break.unwrap_or(i);
+                lines.push(TextLine {
+                    byte_range: byte_at(line_start_idx)..byte_at(break_idx),
+                    width: prefix[break_idx] - prefix[line_start_idx],
+                });
+
+                // Skip leading whitespace on the next line.
+                let mut next = break_idx;
+                while next < n && graphemes[next].1.chars().all(char::is_whitespace) {
+                    next += 1;
+                }
+                line_start_idx = next;
+                last_break = None;
+
+                if i < line_start_idx {
+                    i = line_start_idx;
+                }
+                continue;
+            }
+
+            if graphemes[i].1.chars().all(char::is_whitespace) {
+                last_break = Some(i + 1);
+            }
+
+            i += 1;
+        }
+
+        lines.push(TextLine {
+            byte_range: byte_at(line_start_idx)..line_end,
+            width: prefix[n] - prefix[line_start_idx],
+        });
+    }
+
+    // A trailing `\n` starts an empty final line for a caret placed after it.
+    if text.ends_with('\n') {
+        lines.push(TextLine {
+            byte_range: text.len()..text.len(),
+            width: 0.0,
+        });
+    }
+
+    if lines.is_empty() {
+        lines.push(TextLine::default());
+    }
+
+    lines
+}
+
+/// Splits `text` into paragraphs, keeping the trailing `\n` on each chunk so byte
+/// offsets stay anchored to the original string.
+fn split_inclusive_newlines(text: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    for (offset, _) in text.match_indices('\n') {
+        chunks.push(&text[start..offset + 1]);
+        start = offset + 1;
+    }
+
+    if start < text.len() || chunks.is_empty() {
+        chunks.push(&text[start..]);
+    }
+
+    chunks
+}
+
+/// Returns the index of the visual line that contains `byte_offset`.
+///
+/// A caret sitting exactly on a line's trailing boundary belongs to that line; an
+/// offset past the last line clamps to the final line so the caret stays visible.
+pub fn line_at_byte(lines: &[TextLine], byte_offset: usize) -> usize {
+    for (index, line) in lines.iter().enumerate() {
+        if byte_offset <= line.byte_range.end {
+            return index;
+        }
+    }
+
+    lines.len().saturating_sub(1)
+}
+
 /// The text selection layout is used to measure and arrange a text selection cursor.
 #[derive(Default)]
 pub struct TextSelectionLayout {
     desired_size: RefCell<DirtySize>,
     old_text_selection: Cell<TextSelection>,
+    lines: RefCell<Vec<TextLine>>,
+    measure_cache: FontMeasureCache,
 }
 
 impl TextSelectionLayout {
     pub fn new() -> Self {
         TextSelectionLayout::default()
     }
+
+    /// Returns the visual lines computed by the last `arrange` pass.
+    ///
+    /// Hit-testing (click-to-place-cursor) uses this break list to map a point
+    /// back to a text index.
+    pub fn lines(&self) -> Vec<TextLine> {
+        self.lines.borrow().clone()
+    }
 }
 
 impl Into<Box<dyn Layout>> for TextSelectionLayout {
@@ -109,9 +491,17 @@ impl Layout for TextSelectionLayout {
             return self.desired_size.borrow().size();
         }
 
+        // The measurement cache is keyed by text hash, so it is kept across passes:
+        // a dirty pass triggered by a selection move still reuses every prefix width
+        // measured for the unchanged text. Stale keys are simply never hit again.
+
         let mut pos = 0.0;
+        let mut top = 0.0;
+        let mut selection_width = 0.0;
         let mut size = self.desired_size.borrow().size();
 
+        let constraint = Constraint::get(entity, ecm);
+
         let vertical_alignment = VerticalAlignment::get(entity, ecm);
         let margin = Margin::get(entity, ecm);
 
@@ -124,18 +514,106 @@ impl Layout for TextSelectionLayout {
             let font = widget.get_property::<Font>();
             let font_size = widget.get_property::<FontSize>();
 
+            let direction = if widget.has_property::<TextDirection>() {
+                widget.get_property::<TextDirection>()
+            } else {
+                TextDirection::default()
+            };
+
+            // Styled runs default to an empty list, keeping the plain-`Text` path on a
+            // single font/size measurement.
+            let spans = if widget.has_property::<TextSpans>() {
+                widget.get_property::<TextSpans>().0
+            } else {
+                Vec::new()
+            };
+
+            // Wrap the text to the available width so the caret can be placed at an
+            // arbitrary (line, column); the break list is retained for hit-testing.
+            let lines = wrap_lines(
+                &self.measure_cache,
+                &text.0,
+                &font.0,
+                font_size.0 as u32,
+                constraint.width(),
+            );
+
             if let Ok(selection) = ecm.borrow_component::<TextSelection>(entity) {
-                if let Some(text_part) = text.0.get(0..selection.start_index) {
-                    pos = FONT_MEASURE
-                        .measure(text_part, &font.0, font_size.0 as u32)
-                        .0 as f64;
-
-                    if text_part.ends_with(" ") {
-                        pos +=
-                            (FONT_MEASURE.measure("a", &font.0, font_size.0 as u32).0 / 2) as f64;
-                    }
+                // Grapheme indices are mapped to byte offsets so slicing always lands
+                // on a cluster boundary, independent of multi-byte UTF-8 content.
+                let start_byte = grapheme_to_byte_offset(&text.0, selection.start_index);
+
+                let line_index = line_at_byte(&lines, start_byte);
+                let line = &lines[line_index];
+
+                pos = caret_visual_x(
+                    &self.measure_cache,
+                    &text.0,
+                    line.byte_range.clone(),
+                    start_byte,
+                    &font.0,
+                    font_size.0 as u32,
+                    direction,
+                    &spans,
+                );
+
+                top = line_index as f64 * line_height(font_size.0);
+
+                // `TextSelection` stores a start plus a cluster `length`, so the end
+                // index is derived rather than read directly. A non-zero length means
+                // the selection spans a range and the highlight width is measured; a
+                // zero-length selection keeps the collapsed, zero-width cursor.
+                if selection.length > 0 {
+                    let start = selection.start_index;
+                    let end = selection.start_index + selection.length;
+
+                    let start_byte = grapheme_to_byte_offset(&text.0, start);
+                    let end_byte = grapheme_to_byte_offset(&text.0, end);
+
+                    let line_index = line_at_byte(&lines, start_byte);
+                    let line = &lines[line_index];
+                    // LIMITATION: the highlight is clamped to the line containing the
+                    // selection start, so a selection spanning wrapped or
+                    // `\n`-separated lines reports only that first line's width. A
+                    // per-line highlight set (one rect per covered line) is a
+                    // follow-up; the `lines` break list already exposes what a
+                    // multi-line highlight would need.
+                    let line_end = end_byte.min(line.byte_range.end);
+
+                    // `pos` tracks the left (visual) edge of the selection span.
+                    let start_x = caret_visual_x(
+                        &self.measure_cache,
+                        &text.0,
+                        line.byte_range.clone(),
+                        start_byte,
+                        &font.0,
+                        font_size.0 as u32,
+                        direction,
+                        &spans,
+                    );
+                    let end_x = caret_visual_x(
+                        &self.measure_cache,
+                        &text.0,
+                        line.byte_range.clone(),
+                        line_end,
+                        &font.0,
+                        font_size.0 as u32,
+                        direction,
+                        &spans,
+                    );
+
+                    pos = start_x.min(end_x);
+                    selection_width = (end_x - start_x).abs();
+
+                    top = line_index as f64 * line_height(font_size.0);
                 }
             }
+
+            *self.lines.borrow_mut() = lines;
+        }
+
+        if selection_width > 0.0 {
+            size.0 = selection_width;
         }
 
         if let Ok(off) = ecm.borrow_component::<Offset>(entity) {
@@ -144,6 +622,7 @@ impl Layout for TextSelectionLayout {
 
         if let Ok(margin) = ecm.borrow_mut_component::<Margin>(entity) {
             margin.set_left(pos);
+            margin.set_top(top);
         }
 
         for child in &tree.children[&entity] {
@@ -161,3 +640,77 @@ impl Layout for TextSelectionLayout {
         size
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Width-dependent helpers (`wrap_lines`, `measure_range_spans`, `FontMeasureCache`)
+    // rely on the platform `FONT_MEASURE` and are exercised by integration tests with a
+    // backend; the unit tests below cover the pure boundary logic.
+
+    #[test]
+    fn grapheme_byte_offset_round_trips_multibyte() {
+        // "á" is two bytes, so the second cluster starts at byte 2.
+        let text = "áb";
+        assert_eq!(grapheme_to_byte_offset(text, 0), 0);
+        assert_eq!(grapheme_to_byte_offset(text, 1), 2);
+        // An index past the end clamps to the byte length.
+        assert_eq!(grapheme_to_byte_offset(text, 9), text.len());
+
+        assert_eq!(byte_to_grapheme_offset(text, 0), 0);
+        assert_eq!(byte_to_grapheme_offset(text, 2), 1);
+    }
+
+    #[test]
+    fn grapheme_offset_treats_combining_sequence_as_one_cluster() {
+        // "e" + combining acute accent is a single grapheme cluster (3 bytes).
+        let text = "e\u{0301}x";
+        assert_eq!(grapheme_to_byte_offset(text, 1), 3);
+        assert_eq!(byte_to_grapheme_offset(text, 3), 1);
+    }
+
+    #[test]
+    fn split_inclusive_newlines_keeps_hard_breaks() {
+        assert_eq!(split_inclusive_newlines("a\nb"), vec!["a\n", "b"]);
+        assert_eq!(split_inclusive_newlines("a\n"), vec!["a\n"]);
+        assert_eq!(split_inclusive_newlines(""), vec![""]);
+    }
+
+    #[test]
+    fn directional_runs_attach_neutrals_to_previous_run() {
+        // A space between two LTR words stays in the single LTR run.
+        let runs = directional_runs("a b", 0..3);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].1, TextDirection::LeftToRight);
+        assert_eq!(runs[0].0, 0..3);
+    }
+
+    #[test]
+    fn directional_runs_split_on_strong_direction() {
+        // Latin, then Arabic: two runs with the neutral-free boundary between them.
+        let text = "ab\u{0628}\u{0629}";
+        let runs = directional_runs(text, 0..text.len());
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].1, TextDirection::LeftToRight);
+        assert_eq!(runs[1].1, TextDirection::RightToLeft);
+    }
+
+    #[test]
+    fn line_at_byte_clamps_to_last_line() {
+        let lines = vec![
+            TextLine {
+                byte_range: 0..3,
+                width: 0.0,
+            },
+            TextLine {
+                byte_range: 3..6,
+                width: 0.0,
+            },
+        ];
+        assert_eq!(line_at_byte(&lines, 0), 0);
+        assert_eq!(line_at_byte(&lines, 3), 0);
+        assert_eq!(line_at_byte(&lines, 4), 1);
+        assert_eq!(line_at_byte(&lines, 99), 1);
+    }
+}
@@ -0,0 +1,8 @@
+// The core widget properties (`Text`, `Font`, `FontSize`, `TextSelection`, …) are
+// declared by the property modules (not shown in this snapshot).
+
+mod text_direction;
+mod text_spans;
+
+pub use self::text_direction::TextDirection;
+pub use self::text_spans::{TextSpan, TextSpans};
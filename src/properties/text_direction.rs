@@ -0,0 +1,21 @@
+/// Writing direction applied to a text run when placing the caret.
+///
+/// `Auto` splits each line into directional runs: a line whose runs all share one
+/// direction is placed in that direction, and a mixed line accumulates its runs in
+/// logical order without bidi visual reordering.
+///
+/// Stored as a widget component like the other text properties, so it is read with
+/// `WidgetContainer::get_property`/`has_property`; the `Clone`/`Default` impls below
+/// are what that accessor path requires.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextDirection {
+    LeftToRight,
+    RightToLeft,
+    Auto,
+}
+
+impl Default for TextDirection {
+    fn default() -> Self {
+        TextDirection::Auto
+    }
+}
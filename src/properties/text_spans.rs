@@ -0,0 +1,21 @@
+use std::ops::Range;
+
+/// A styled run of text: a byte range rendered with its own font and size.
+#[derive(Clone, Debug)]
+pub struct TextSpan {
+    pub range: Range<usize>,
+    pub font: String,
+    pub font_size: f64,
+}
+
+/// An ordered list of styled runs attached to a `Text`.
+///
+/// The plain-`Text` path uses an empty list, which falls back to the widget's
+/// single `Font`/`FontSize`; a non-empty list lets rich text (e.g. rendered
+/// markdown) report accurate caret and selection geometry across mixed runs.
+///
+/// Stored as a widget component like the other text properties, so the
+/// `WidgetContainer::get_property`/`has_property` accessor used by the layout
+/// resolves against its `Clone`/`Default` impls.
+#[derive(Clone, Debug, Default)]
+pub struct TextSpans(pub Vec<TextSpan>);